@@ -0,0 +1,176 @@
+//! Content-addressed caching of per-function compilation results, so that
+//! recompiling a module whose function bodies are unchanged can skip
+//! straight to a previously compiled result instead of re-running
+//! translation and codegen.
+
+use crate::translator::CraneliftUnwindInfo;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use wasmer_types::CompiledFunction;
+
+/// A content-addressed store for compiled function bodies. Implementations
+/// may back this with an in-memory map, a file on disk, or a shared remote
+/// cache; `compile_module` only needs byte-key lookup and insertion.
+pub trait CacheStore: Send + Sync {
+    /// Looks up a previously cached, serialized cache entry by digest.
+    fn get(&self, digest: &[u8]) -> Option<Vec<u8>>;
+    /// Stores a serialized cache entry under `digest`.
+    fn put(&self, digest: &[u8], value: &[u8]);
+}
+
+/// Everything that can affect the generated code for a function body. This
+/// is kept alongside the compiled result and re-checked on every lookup, so
+/// that a 64-bit digest collision between two distinct functions degrades to
+/// a cache miss instead of silently returning the wrong machine code.
+#[derive(Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CacheKeyMaterial {
+    body: Vec<u8>,
+    isa_flags: String,
+    triple: String,
+    middleware_chain_identity: Vec<usize>,
+    signature: String,
+}
+
+impl CacheKeyMaterial {
+    pub fn new(
+        body: &[u8],
+        isa_flags: impl Debug,
+        triple: impl Debug,
+        middleware_chain_identity: &[usize],
+        signature: impl Debug,
+    ) -> Self {
+        Self {
+            body: body.to_vec(),
+            isa_flags: format!("{:?}", isa_flags),
+            triple: format!("{:?}", triple),
+            middleware_chain_identity: middleware_chain_identity.to_vec(),
+            signature: format!("{:?}", signature),
+        }
+    }
+
+    /// The short digest used as the `CacheStore` lookup key. Never trusted on
+    /// its own: callers must still compare the full `CacheKeyMaterial` of a
+    /// hit against this one before using its payload.
+    fn digest(&self) -> [u8; 8] {
+        let mut hasher = DefaultHasher::new();
+        self.body.hash(&mut hasher);
+        self.isa_flags.hash(&mut hasher);
+        self.triple.hash(&mut hasher);
+        self.middleware_chain_identity.hash(&mut hasher);
+        self.signature.hash(&mut hasher);
+        hasher.finish().to_le_bytes()
+    }
+}
+
+/// Borrowed view of a cache entry, used only for serializing on `put` so
+/// storing a result doesn't require cloning the `CompiledFunction`.
+///
+/// `unwind` is stored alongside `function` — not folded into it — because a
+/// function whose unwind info must become an `.eh_frame` FDE needs that
+/// pre-FDE-conversion form to re-derive the FDE on a later hit; without it,
+/// serving this function from cache would silently drop it from the
+/// module's unwind table (see `compiler.rs`'s per-function compile loop).
+#[derive(serde::Serialize)]
+struct CacheEntryRef<'a> {
+    key: &'a CacheKeyMaterial,
+    function: &'a CompiledFunction,
+    unwind: &'a CraneliftUnwindInfo,
+}
+
+#[derive(serde::Deserialize)]
+struct CacheEntryOwned {
+    key: CacheKeyMaterial,
+    function: CompiledFunction,
+    unwind: CraneliftUnwindInfo,
+}
+
+/// Looks up `key` in `store`, verifying that the stored entry's full key
+/// material matches before returning its function and unwind info. A digest
+/// collision (two distinct `CacheKeyMaterial`s sharing a digest) is treated
+/// as a miss.
+pub fn load_cached_function(
+    store: &dyn CacheStore,
+    key: &CacheKeyMaterial,
+) -> Option<(CompiledFunction, CraneliftUnwindInfo)> {
+    let bytes = store.get(&key.digest())?;
+    let entry: CacheEntryOwned = bincode::deserialize(&bytes).ok()?;
+    if entry.key != *key {
+        return None;
+    }
+    Some((entry.function, entry.unwind))
+}
+
+/// Serializes `function` and its unwind info together with the key material
+/// that produced them, and stores the result under `key`'s digest, so a
+/// future lookup can verify the hit and still contribute a valid FDE.
+pub fn store_compiled_function(
+    store: &dyn CacheStore,
+    key: &CacheKeyMaterial,
+    function: &CompiledFunction,
+    unwind: &CraneliftUnwindInfo,
+) {
+    let entry = CacheEntryRef {
+        key,
+        function,
+        unwind,
+    };
+    if let Ok(bytes) = bincode::serialize(&entry) {
+        store.put(&key.digest(), &bytes);
+    }
+}
+
+#[cfg(test)]
+mod cache_key_material_tests {
+    use super::*;
+
+    fn key(body: &[u8], signature: &str) -> CacheKeyMaterial {
+        CacheKeyMaterial::new(body, "flags", "triple", &[1, 2], signature)
+    }
+
+    #[test]
+    fn identical_inputs_produce_equal_key_material() {
+        assert_eq!(key(b"body", "sig"), key(b"body", "sig"));
+    }
+
+    #[test]
+    fn distinct_bodies_produce_unequal_key_material() {
+        // Two distinct `CacheKeyMaterial`s can still share an 8-byte digest;
+        // `load_cached_function`'s full-equality check is what turns that
+        // collision into a miss instead of returning the wrong function. This
+        // pins that the full key material itself still tells the two apart,
+        // independent of whatever digests they happen to hash to.
+        let a = key(b"body-a", "sig");
+        let b = key(b"body-b", "sig");
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn every_field_that_can_affect_codegen_is_covered_by_equality() {
+        // `load_cached_function` trusts a digest hit only after this
+        // `PartialEq` passes, so every field listed in the struct doc as
+        // "everything that can affect the generated code" must actually
+        // participate in it — otherwise a changed input could collide with a
+        // stale cache entry and be served as a hit.
+        let base = key(b"body", "sig");
+        assert_ne!(base, CacheKeyMaterial::new(b"other-body", "flags", "triple", &[1, 2], "sig"));
+        assert_ne!(
+            base,
+            CacheKeyMaterial::new(b"body", "other-flags", "triple", &[1, 2], "sig")
+        );
+        assert_ne!(
+            base,
+            CacheKeyMaterial::new(b"body", "flags", "other-triple", &[1, 2], "sig")
+        );
+        assert_ne!(base, CacheKeyMaterial::new(b"body", "flags", "triple", &[1, 9], "sig"));
+        assert_ne!(
+            base,
+            CacheKeyMaterial::new(b"body", "flags", "triple", &[1, 2], "other-sig")
+        );
+    }
+
+    #[test]
+    fn digest_is_deterministic_for_equal_key_material() {
+        assert_eq!(key(b"body", "sig").digest(), key(b"body", "sig").digest());
+    }
+}
@@ -1,7 +1,9 @@
 //! Support for compiling with Cranelift.
 
 use crate::address_map::get_function_address_map;
+use crate::cache::{load_cached_function, store_compiled_function, CacheKeyMaterial};
 use crate::config::Cranelift;
+use crate::debug::{generate_debug_info, FunctionAddressTransform};
 #[cfg(feature = "unwind")]
 use crate::dwarf::WriterRelocate;
 use crate::func_environ::{get_function_name, FuncEnvironment};
@@ -15,7 +17,7 @@ use crate::translator::{
 use cranelift_codegen::ir::ExternalName;
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::{ir, MachReloc};
-use cranelift_codegen::{Context, MachTrap};
+use cranelift_codegen::{Context, MachStackMap, MachTrap};
 #[cfg(feature = "unwind")]
 use gimli::write::{Address, EhFrame, FrameTable};
 #[cfg(feature = "rayon")]
@@ -26,11 +28,22 @@ use wasmer_compiler::{
     ModuleMiddlewareChain, ModuleTranslationState,
 };
 use wasmer_types::entity::{EntityRef, PrimaryMap};
+// BLOCKING DEPENDENCY, not yet landed: `TrapInformation::srcloc`,
+// `CompiledFunctionFrameInfo::stack_maps`, `StackMapInformation`,
+// `RelocationTarget::{CustomSection, Builtin}`, `BuiltinIndex`, and
+// `ModuleMemoryOffset` belong to `wasmer_types` (`lib/types`), a sibling
+// crate in this workspace that this checkout doesn't include. This crate
+// cannot build on its own until that crate's PR adding those items lands;
+// this comment is a tracking marker for that dependency, not a substitute
+// for it. Do not attempt to shadow or locally redefine these types here —
+// the real ones need to be shared with every other crate that also depends
+// on `wasmer_types` (the engine, the VM), not private to this compiler.
 use wasmer_types::{
-    CallingConvention, Compilation, CompileError, CompileModuleInfo, CompiledFunction,
-    CompiledFunctionFrameInfo, CompiledFunctionUnwindInfo, Dwarf, FunctionBody, FunctionIndex,
-    LocalFunctionIndex, ModuleInfo, Relocation, RelocationTarget, SectionIndex, SignatureIndex,
-    Target, TrapCode, TrapInformation,
+    BuiltinIndex, CallingConvention, Compilation, CompileError, CompileModuleInfo,
+    CompiledFunction, CompiledFunctionFrameInfo, CompiledFunctionUnwindInfo, Dwarf,
+    FunctionAddressMap, FunctionBody, FunctionIndex, LocalFunctionIndex, ModuleInfo,
+    ModuleMemoryOffset, Relocation, RelocationTarget, SectionIndex, SignatureIndex,
+    StackMapInformation, Target, TrapCode, TrapInformation,
 };
 
 /// A compiler that compiles a WebAssembly module with Cranelift, translating the Wasm to Cranelift IR,
@@ -106,6 +119,25 @@ impl Compiler for CraneliftCompiler {
 
         let mut custom_sections = PrimaryMap::new();
 
+        // Whether each function's unwind info needs to be converted into an
+        // FDE for the module's shared `.eh_frame` section. Read by both the
+        // fresh-compile and cache-hit paths below so a hit reconstructs the
+        // same FDE a fresh compile would have produced.
+        #[cfg(feature = "unwind")]
+        let needs_fde = dwarf_frametable.is_some();
+        #[cfg(not(feature = "unwind"))]
+        let needs_fde = false;
+
+        // Identifies the middleware chain so a cache key can't collide across
+        // two `Cranelift` configs whose middlewares differ but whose function
+        // bodies happen to match byte-for-byte.
+        let middleware_chain_identity: Vec<usize> = self
+            .config
+            .middlewares
+            .iter()
+            .map(|middleware| Arc::as_ptr(middleware) as *const () as usize)
+            .collect();
+
         #[cfg(not(feature = "rayon"))]
         let mut func_translator = FuncTranslator::new();
         #[cfg(not(feature = "rayon"))]
@@ -115,6 +147,31 @@ impl Compiler for CraneliftCompiler {
             .into_iter()
             .map(|(i, input)| {
                 let func_index = module.func_index(i);
+
+                // A cache hit skips translation and codegen entirely: the key
+                // material already covers everything that can affect the
+                // generated code for this body, so a verified hit can never
+                // be stale. The cached unwind info (pre-FDE-conversion) lets
+                // a hit still contribute a correct FDE below, the same way a
+                // fresh compile does.
+                let cache_key = self.config.cache.is_some().then(|| {
+                    CacheKeyMaterial::new(
+                        input.data,
+                        isa.flags(),
+                        target.triple(),
+                        &middleware_chain_identity,
+                        &signatures[module.functions[func_index]],
+                    )
+                });
+                if let (Some(cache), Some(key)) =
+                    (self.config.cache.as_deref(), cache_key.as_ref())
+                {
+                    if let Some((cached, unwind)) = load_cached_function(cache, key) {
+                        let (_, fde) = unwind_and_fde(unwind, needs_fde, i);
+                        return Ok((cached, fde));
+                    }
+                }
+
                 let mut context = Context::new();
                 let mut func_env = FuncEnvironment::new(
                     isa.frontend_config(),
@@ -123,11 +180,12 @@ impl Compiler for CraneliftCompiler {
                     &memory_styles,
                     &table_styles,
                 );
+                func_env.set_interrupts(self.config.enable_interrupts, self.config.interrupt_granularity);
                 context.func.name = get_function_name(func_index);
                 context.func.signature = signatures[module.functions[func_index]].clone();
-                // if generate_debug_info {
-                //     context.func.collect_debug_info();
-                // }
+                if self.config.debug_info {
+                    context.func.collect_debug_info();
+                }
                 let mut reader =
                     MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
                 reader.set_middleware_chain(
@@ -157,54 +215,50 @@ impl Compiler for CraneliftCompiler {
                     .map(|r| mach_reloc_to_reloc(module, r))
                     .collect::<Vec<_>>();
 
+                let unwind_result = compiled_function_unwind_info(&*isa, &context)?;
+                let (unwind_info, fde) = unwind_and_fde(unwind_result.clone(), needs_fde, i);
+
+                let range = reader.range();
+                let address_map = get_function_address_map(&context, range, code_buf.len());
+
+                // The address map must be computed before the traps, since each trap's
+                // Wasm source offset is resolved from the instruction that precedes it.
                 let traps = result
                     .buffer
                     .traps()
                     .into_iter()
-                    .map(mach_trap_to_trap)
+                    .map(|trap| mach_trap_to_trap(trap, &address_map))
                     .collect::<Vec<_>>();
 
-                let (unwind_info, fde) = match compiled_function_unwind_info(&*isa, &context)? {
-                    #[cfg(feature = "unwind")]
-                    CraneliftUnwindInfo::Fde(fde) => {
-                        if dwarf_frametable.is_some() {
-                            let fde = fde.to_fde(Address::Symbol {
-                                // The symbol is the kind of relocation.
-                                // "0" is used for functions
-                                symbol: WriterRelocate::FUNCTION_SYMBOL,
-                                // We use the addend as a way to specify the
-                                // function index
-                                addend: i.index() as _,
-                            });
-                            // The unwind information is inserted into the dwarf section
-                            (Some(CompiledFunctionUnwindInfo::Dwarf), Some(fde))
-                        } else {
-                            (None, None)
-                        }
-                    }
-                    #[cfg(feature = "unwind")]
-                    other => (other.maybe_into_to_windows_unwind(), None),
+                // Every safepoint (call site / allocation point) that can trigger a
+                // collection carries a stack map recording which stack slots hold
+                // live reference-typed values at that offset.
+                let stack_maps = result
+                    .buffer
+                    .stack_maps()
+                    .into_iter()
+                    .map(mach_stack_map_to_stack_map_information)
+                    .collect::<Vec<_>>();
 
-                    // This is a bit hacky, but necessary since gimli is not
-                    // available when the "unwind" feature is disabled.
-                    #[cfg(not(feature = "unwind"))]
-                    other => (other.maybe_into_to_windows_unwind(), None::<()>),
+                let compiled_function = CompiledFunction {
+                    body: FunctionBody {
+                        body: code_buf,
+                        unwind_info,
+                    },
+                    relocations: func_relocs,
+                    frame_info: CompiledFunctionFrameInfo {
+                        address_map,
+                        traps,
+                        stack_maps,
+                    },
                 };
+                if let (Some(cache), Some(key)) =
+                    (self.config.cache.as_deref(), cache_key.as_ref())
+                {
+                    store_compiled_function(cache, key, &compiled_function, &unwind_result);
+                }
 
-                let range = reader.range();
-                let address_map = get_function_address_map(&context, range, code_buf.len());
-
-                Ok((
-                    CompiledFunction {
-                        body: FunctionBody {
-                            body: code_buf,
-                            unwind_info,
-                        },
-                        relocations: func_relocs,
-                        frame_info: CompiledFunctionFrameInfo { address_map, traps },
-                    },
-                    fde,
-                ))
+                Ok((compiled_function, fde))
             })
             .collect::<Result<Vec<_>, CompileError>>()?
             .into_iter()
@@ -216,6 +270,31 @@ impl Compiler for CraneliftCompiler {
             .par_iter()
             .map_init(FuncTranslator::new, |func_translator, (i, input)| {
                 let func_index = module.func_index(*i);
+
+                // A cache hit skips translation and codegen entirely: the key
+                // material already covers everything that can affect the
+                // generated code for this body, so a verified hit can never
+                // be stale. The cached unwind info (pre-FDE-conversion) lets
+                // a hit still contribute a correct FDE below, the same way a
+                // fresh compile does.
+                let cache_key = self.config.cache.is_some().then(|| {
+                    CacheKeyMaterial::new(
+                        input.data,
+                        isa.flags(),
+                        target.triple(),
+                        &middleware_chain_identity,
+                        &signatures[module.functions[func_index]],
+                    )
+                });
+                if let (Some(cache), Some(key)) =
+                    (self.config.cache.as_deref(), cache_key.as_ref())
+                {
+                    if let Some((cached, unwind)) = load_cached_function(cache, key) {
+                        let (_, fde) = unwind_and_fde(unwind, needs_fde, *i);
+                        return Ok((cached, fde));
+                    }
+                }
+
                 let mut context = Context::new();
                 let mut func_env = FuncEnvironment::new(
                     isa.frontend_config(),
@@ -224,11 +303,12 @@ impl Compiler for CraneliftCompiler {
                     memory_styles,
                     table_styles,
                 );
+                func_env.set_interrupts(self.config.enable_interrupts, self.config.interrupt_granularity);
                 context.func.name = get_function_name(func_index);
                 context.func.signature = signatures[module.functions[func_index]].clone();
-                // if generate_debug_info {
-                //     context.func.collect_debug_info();
-                // }
+                if self.config.debug_info {
+                    context.func.collect_debug_info();
+                }
                 let mut reader =
                     MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
                 reader.set_middleware_chain(
@@ -258,54 +338,50 @@ impl Compiler for CraneliftCompiler {
                     .map(|r| mach_reloc_to_reloc(module, r))
                     .collect::<Vec<_>>();
 
+                let unwind_result = compiled_function_unwind_info(&*isa, &context)?;
+                let (unwind_info, fde) = unwind_and_fde(unwind_result.clone(), needs_fde, *i);
+
+                let range = reader.range();
+                let address_map = get_function_address_map(&context, range, code_buf.len());
+
+                // The address map must be computed before the traps, since each trap's
+                // Wasm source offset is resolved from the instruction that precedes it.
                 let traps = result
                     .buffer
                     .traps()
                     .iter()
-                    .map(mach_trap_to_trap)
+                    .map(|trap| mach_trap_to_trap(trap, &address_map))
                     .collect::<Vec<_>>();
 
-                let (unwind_info, fde) = match compiled_function_unwind_info(&*isa, &context)? {
-                    #[cfg(feature = "unwind")]
-                    CraneliftUnwindInfo::Fde(fde) => {
-                        if dwarf_frametable.is_some() {
-                            let fde = fde.to_fde(Address::Symbol {
-                                // The symbol is the kind of relocation.
-                                // "0" is used for functions
-                                symbol: WriterRelocate::FUNCTION_SYMBOL,
-                                // We use the addend as a way to specify the
-                                // function index
-                                addend: i.index() as _,
-                            });
-                            // The unwind information is inserted into the dwarf section
-                            (Some(CompiledFunctionUnwindInfo::Dwarf), Some(fde))
-                        } else {
-                            (None, None)
-                        }
-                    }
-                    #[cfg(feature = "unwind")]
-                    other => (other.maybe_into_to_windows_unwind(), None),
+                // Every safepoint (call site / allocation point) that can trigger a
+                // collection carries a stack map recording which stack slots hold
+                // live reference-typed values at that offset.
+                let stack_maps = result
+                    .buffer
+                    .stack_maps()
+                    .iter()
+                    .map(mach_stack_map_to_stack_map_information)
+                    .collect::<Vec<_>>();
 
-                    // This is a bit hacky, but necessary since gimli is not
-                    // available when the "unwind" feature is disabled.
-                    #[cfg(not(feature = "unwind"))]
-                    other => (other.maybe_into_to_windows_unwind(), None::<()>),
+                let compiled_function = CompiledFunction {
+                    body: FunctionBody {
+                        body: code_buf,
+                        unwind_info,
+                    },
+                    relocations: func_relocs,
+                    frame_info: CompiledFunctionFrameInfo {
+                        address_map,
+                        traps,
+                        stack_maps,
+                    },
                 };
+                if let (Some(cache), Some(key)) =
+                    (self.config.cache.as_deref(), cache_key.as_ref())
+                {
+                    store_compiled_function(cache, key, &compiled_function, &unwind_result);
+                }
 
-                let range = reader.range();
-                let address_map = get_function_address_map(&context, range, code_buf.len());
-
-                Ok((
-                    CompiledFunction {
-                        body: FunctionBody {
-                            body: code_buf,
-                            unwind_info,
-                        },
-                        relocations: func_relocs,
-                        frame_info: CompiledFunctionFrameInfo { address_map, traps },
-                    },
-                    fde,
-                ))
+                Ok((compiled_function, fde))
             })
             .collect::<Result<Vec<_>, CompileError>>()?
             .into_iter()
@@ -328,6 +404,44 @@ impl Compiler for CraneliftCompiler {
         #[cfg(not(feature = "unwind"))]
         let dwarf = None;
 
+        // Rewrite the Wasm module's DWARF into native DWARF pointing at the
+        // machine code we just generated, so a debugger attached to the
+        // runtime can step through the original Wasm/source lines. Only done
+        // when requested, since transforming DWARF is relatively expensive
+        // and most embeddings never attach a debugger.
+        if self.config.debug_info {
+            let module_memory_offset = ModuleMemoryOffset::None;
+            // Functions are emitted into the module's text section in the
+            // same order they appear in `functions` (see `Compilation::new`
+            // below), back to back with no padding, so each function's
+            // offset within that concatenated section is just the running
+            // total of the bodies that precede it.
+            let mut next_body_offset = 0u32;
+            let address_transforms: Vec<FunctionAddressTransform> = functions
+                .iter()
+                .enumerate()
+                .map(|(index, f)| {
+                    let body_offset = next_body_offset;
+                    next_body_offset += f.body.body.len() as u32;
+                    FunctionAddressTransform {
+                        local_index: LocalFunctionIndex::new(index),
+                        body_offset,
+                        body_len: f.body.body.len() as u32,
+                        address_map: f.frame_info.address_map.clone(),
+                    }
+                })
+                .collect();
+            if let Some(transformed) = generate_debug_info(
+                module.raw_wasm_debug_sections(),
+                &address_transforms,
+                &module_memory_offset,
+            ) {
+                custom_sections.push(transformed.debug_info.into());
+                custom_sections.push(transformed.debug_line.into());
+                custom_sections.push(transformed.debug_abbrev.into());
+            }
+        }
+
         // function call trampolines (only for local functions, by signature)
         #[cfg(not(feature = "rayon"))]
         let mut cx = FunctionBuilderContext::new();
@@ -390,6 +504,51 @@ impl Compiler for CraneliftCompiler {
     }
 }
 
+/// Derives a function's `CompiledFunctionUnwindInfo` and, when `need_fde` is
+/// set, its `.eh_frame` FDE, from Cranelift's native unwind representation.
+/// Shared between the fresh-compile and cache-hit paths so a cache hit
+/// reconstructs exactly the FDE a fresh compile would have produced.
+#[cfg(feature = "unwind")]
+fn unwind_and_fde(
+    unwind: CraneliftUnwindInfo,
+    need_fde: bool,
+    func_index: LocalFunctionIndex,
+) -> (
+    Option<CompiledFunctionUnwindInfo>,
+    Option<gimli::write::FrameDescriptionEntry>,
+) {
+    match unwind {
+        CraneliftUnwindInfo::Fde(fde) => {
+            if need_fde {
+                let fde = fde.to_fde(Address::Symbol {
+                    // The symbol is the kind of relocation.
+                    // "0" is used for functions
+                    symbol: WriterRelocate::FUNCTION_SYMBOL,
+                    // We use the addend as a way to specify the
+                    // function index
+                    addend: func_index.index() as _,
+                });
+                // The unwind information is inserted into the dwarf section
+                (Some(CompiledFunctionUnwindInfo::Dwarf), Some(fde))
+            } else {
+                (None, None)
+            }
+        }
+        other => (other.maybe_into_to_windows_unwind(), None),
+    }
+}
+
+// This is a bit hacky, but necessary since gimli is not available when the
+// "unwind" feature is disabled.
+#[cfg(not(feature = "unwind"))]
+fn unwind_and_fde(
+    unwind: CraneliftUnwindInfo,
+    _need_fde: bool,
+    _func_index: LocalFunctionIndex,
+) -> (Option<CompiledFunctionUnwindInfo>, Option<()>) {
+    (unwind.maybe_into_to_windows_unwind(), None)
+}
+
 fn mach_reloc_to_reloc(module: &ModuleInfo, reloc: &MachReloc) -> Relocation {
     let &MachReloc {
         offset,
@@ -397,17 +556,30 @@ fn mach_reloc_to_reloc(module: &ModuleInfo, reloc: &MachReloc) -> Relocation {
         ref name,
         addend,
     } = reloc;
-    let reloc_target = if let ExternalName::User { namespace, index } = *name {
-        debug_assert_eq!(namespace, 0);
-        RelocationTarget::LocalFunc(
+    let reloc_target = match *name {
+        ExternalName::User { namespace: 0, index } => RelocationTarget::LocalFunc(
             module
                 .local_func_index(FunctionIndex::from_u32(index))
                 .expect("The provided function should be local"),
-        )
-    } else if let ExternalName::LibCall(libcall) = *name {
-        RelocationTarget::LibCall(irlibcall_to_libcall(libcall))
-    } else {
-        panic!("unrecognized external name")
+        ),
+        // Namespace 1 references out-of-line read-only data spilled into a
+        // `custom_sections` entry, e.g. large switch-table jump targets.
+        // `FuncEnvironment::custom_section_reference` builds such names, but
+        // no translation path calls it, so compiled functions do not emit them.
+        ExternalName::User { namespace: 1, index } => {
+            RelocationTarget::CustomSection(SectionIndex::from_u32(index))
+        }
+        // Namespace 2 references a VM builtin helper function, giving calls
+        // into the runtime a stable ABI instead of routing them through
+        // libcalls.
+        ExternalName::User { namespace: 2, index } => {
+            RelocationTarget::Builtin(BuiltinIndex::from_u32(index))
+        }
+        ExternalName::User { namespace, .. } => {
+            panic!("unrecognized external name namespace {}", namespace)
+        }
+        ExternalName::LibCall(libcall) => RelocationTarget::LibCall(irlibcall_to_libcall(libcall)),
+        _ => panic!("unrecognized external name"),
     };
     Relocation {
         kind: irreloc_to_relocationkind(kind),
@@ -417,14 +589,86 @@ fn mach_reloc_to_reloc(module: &ModuleInfo, reloc: &MachReloc) -> Relocation {
     }
 }
 
-fn mach_trap_to_trap(trap: &MachTrap) -> TrapInformation {
+#[cfg(test)]
+mod mach_reloc_tests {
+    use super::*;
+
+    // Namespace 0 (`RelocationTarget::LocalFunc`) isn't exercised here since
+    // decoding it needs a real `ModuleInfo` to resolve the function index
+    // against; namespaces 1 and 2 are pure, module-independent lookups and
+    // are exactly the decoder paths this series' producers (still unwired —
+    // see `func_environ.rs`) are meant to feed.
+
+    #[test]
+    fn decodes_namespace_1_as_a_custom_section_reference() {
+        let module = ModuleInfo::default();
+        let reloc = MachReloc {
+            offset: 4,
+            kind: cranelift_codegen::binemit::Reloc::Abs8,
+            name: ExternalName::User {
+                namespace: 1,
+                index: 7,
+            },
+            addend: 0,
+        };
+        let relocation = mach_reloc_to_reloc(&module, &reloc);
+        assert_eq!(
+            relocation.reloc_target,
+            RelocationTarget::CustomSection(SectionIndex::from_u32(7))
+        );
+    }
+
+    #[test]
+    fn decodes_namespace_2_as_a_builtin_reference() {
+        let module = ModuleInfo::default();
+        let reloc = MachReloc {
+            offset: 4,
+            kind: cranelift_codegen::binemit::Reloc::Abs8,
+            name: ExternalName::User {
+                namespace: 2,
+                index: 3,
+            },
+            addend: 0,
+        };
+        let relocation = mach_reloc_to_reloc(&module, &reloc);
+        assert_eq!(
+            relocation.reloc_target,
+            RelocationTarget::Builtin(BuiltinIndex::from_u32(3))
+        );
+    }
+}
+
+fn mach_trap_to_trap(trap: &MachTrap, address_map: &FunctionAddressMap) -> TrapInformation {
     let &MachTrap { offset, code } = trap;
     TrapInformation {
         code_offset: offset,
         trap_code: translate_ir_trapcode(code),
+        srcloc: trap_srcloc(address_map, offset),
     }
 }
 
+fn mach_stack_map_to_stack_map_information(stack_map: &MachStackMap) -> StackMapInformation {
+    StackMapInformation {
+        code_offset: stack_map.offset,
+        mapped_words: stack_map.map.mapped_words(),
+        stack_slots: stack_map.map.as_slice().to_vec(),
+    }
+}
+
+/// Resolves the Wasm source offset for a trap recorded at `code_offset`, by walking
+/// the function's address map backwards to the most recent instruction that was
+/// emitted before (or at) that offset. Traps that land before the first tracked
+/// instruction (e.g. in a prologue) fall back to the function's start offset.
+fn trap_srcloc(address_map: &FunctionAddressMap, code_offset: u32) -> u32 {
+    address_map
+        .instructions
+        .iter()
+        .rev()
+        .find(|entry| entry.code_offset as u32 <= code_offset)
+        .map(|entry| entry.srcloc.bits())
+        .unwrap_or_else(|| address_map.start_srcloc.bits())
+}
+
 /// Translates the Cranelift IR TrapCode into generic Trap Code
 fn translate_ir_trapcode(trap: ir::TrapCode) -> TrapCode {
     match trap {
@@ -438,9 +682,57 @@ fn translate_ir_trapcode(trap: ir::TrapCode) -> TrapCode {
         ir::TrapCode::IntegerDivisionByZero => TrapCode::IntegerDivisionByZero,
         ir::TrapCode::BadConversionToInteger => TrapCode::BadConversionToInteger,
         ir::TrapCode::UnreachableCodeReached => TrapCode::UnreachableCodeReached,
-        ir::TrapCode::Interrupt => unimplemented!("Interrupts not supported"),
+        // Emitted by the interrupt check that `FuncEnvironment` inserts at
+        // function entry when `Cranelift::enable_interrupts` is set; the host
+        // flips the shared interruption flag and an instance unwinds through
+        // this trap the next time it enters a function. No checks are emitted
+        // at loop back-edges, so a call already running inside a loop does not
+        // observe the flag.
+        ir::TrapCode::Interrupt => TrapCode::Interrupt,
         ir::TrapCode::User(_user_code) => unimplemented!("User trap code not supported"),
-        // ir::TrapCode::Interrupt => TrapCode::Interrupt,
         // ir::TrapCode::User(user_code) => TrapCode::User(user_code),
     }
 }
+
+#[cfg(test)]
+mod trap_srcloc_tests {
+    use super::trap_srcloc;
+    use wasmer_types::{FunctionAddressMap, InstructionAddressMap, SourceLoc};
+
+    fn address_map(instructions: Vec<(u32, u32)>, start: u32) -> FunctionAddressMap {
+        FunctionAddressMap {
+            instructions: instructions
+                .into_iter()
+                .map(|(code_offset, srcloc)| InstructionAddressMap {
+                    srcloc: SourceLoc::new(srcloc),
+                    code_offset,
+                    code_len: 1,
+                })
+                .collect(),
+            start_srcloc: SourceLoc::new(start),
+            end_srcloc: SourceLoc::new(start + 1),
+            body_offset: 0,
+            body_len: 0,
+        }
+    }
+
+    #[test]
+    fn resolves_to_the_instruction_at_or_before_the_trap() {
+        let map = address_map(vec![(0, 10), (4, 20), (8, 30)], 1);
+        assert_eq!(trap_srcloc(&map, 8), 30);
+        assert_eq!(trap_srcloc(&map, 6), 20);
+        assert_eq!(trap_srcloc(&map, 4), 20);
+    }
+
+    #[test]
+    fn falls_back_to_the_function_start_before_the_first_instruction() {
+        let map = address_map(vec![(4, 20), (8, 30)], 1);
+        assert_eq!(trap_srcloc(&map, 0), 1);
+    }
+
+    #[test]
+    fn falls_back_when_there_are_no_instructions_at_all() {
+        let map = address_map(vec![], 7);
+        assert_eq!(trap_srcloc(&map, 100), 7);
+    }
+}
@@ -0,0 +1,95 @@
+//! Configuration for the Cranelift compiler backend.
+
+use crate::cache::CacheStore;
+use cranelift_codegen::isa::{self, TargetIsa};
+use cranelift_codegen::settings::{self, Configurable};
+use std::sync::Arc;
+use wasmer_compiler::{ModuleMiddleware, ModuleMiddlewareChain};
+use wasmer_types::Target;
+
+/// How often a running function checks the cooperative interruption flag.
+/// Finer granularity bounds worst-case interrupt latency at the cost of a
+/// few extra instructions per iteration; coarser granularity is cheaper for
+/// tight loops that are known to be short-lived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptGranularity {
+    /// Insert a check at every function entry. No checks are emitted at loop
+    /// back-edges, so a call already running inside a loop does not observe
+    /// the flag.
+    EveryBackEdge,
+    /// Accepted, but since no loop back-edge checks are emitted this
+    /// generates the same code as `EveryBackEdge`: a check at every function
+    /// entry only.
+    CounterThreshold(u32),
+}
+
+/// The compiler configuration used by [`crate::compiler::CraneliftCompiler`].
+#[derive(Clone)]
+pub struct Cranelift {
+    pub(crate) middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    /// Emits native DWARF derived from the Wasm module's own `.debug_*`
+    /// sections, so a debugger can step through Wasm/source lines.
+    pub(crate) debug_info: bool,
+    /// Optional content-addressed store for per-function compilation
+    /// results; see [`crate::cache`].
+    pub(crate) cache: Option<Arc<dyn CacheStore>>,
+    /// Enables the cooperative interruption check `FuncEnvironment` inserts
+    /// at function entry. No checks are inserted at loop back-edges.
+    pub(crate) enable_interrupts: bool,
+    pub(crate) interrupt_granularity: InterruptGranularity,
+}
+
+impl Cranelift {
+    pub fn new() -> Self {
+        Self {
+            middlewares: vec![],
+            debug_info: false,
+            cache: None,
+            enable_interrupts: false,
+            interrupt_granularity: InterruptGranularity::EveryBackEdge,
+        }
+    }
+
+    /// Emit native DWARF derived from the Wasm module's debug sections.
+    pub fn debug_info(&mut self, enable: bool) -> &mut Self {
+        self.debug_info = enable;
+        self
+    }
+
+    /// Configure a content-addressed cache for per-function compilation
+    /// results.
+    pub fn cache(&mut self, cache: Option<Arc<dyn CacheStore>>) -> &mut Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Enable cooperative interruption checks, at the given granularity.
+    pub fn enable_interrupts(&mut self, granularity: InterruptGranularity) -> &mut Self {
+        self.enable_interrupts = true;
+        self.interrupt_granularity = granularity;
+        self
+    }
+
+    pub fn push_middleware(&mut self, middleware: Arc<dyn ModuleMiddleware>) -> &mut Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// Builds the Cranelift `TargetIsa` for `target`, turning on the flags
+    /// this configuration depends on (e.g. safepoints for precise stack
+    /// maps of reference-typed values).
+    pub fn isa(&self, target: &Target) -> Result<Box<dyn TargetIsa>, settings::SetError> {
+        let mut builder = settings::builder();
+        builder.set("enable_safepoints", "true")?;
+        let flags = settings::Flags::new(builder);
+        let isa_builder =
+            isa::lookup(target.triple().clone()).expect("Unsupported target for Cranelift");
+        Ok(isa_builder.finish(flags))
+    }
+}
+
+impl Default for Cranelift {
+    fn default() -> Self {
+        Self::new()
+    }
+}
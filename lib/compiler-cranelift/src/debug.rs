@@ -0,0 +1,287 @@
+//! Transforms the Wasm module's `.debug_*` custom sections into native DWARF
+//! that points at the machine code Cranelift generated, so that a debugger
+//! attached to the runtime can step through the original Wasm/source lines.
+//!
+//! Only the line-number program (`.debug_line`) is rewritten today: that's
+//! the piece a debugger needs to map a program counter to a source line and
+//! to single-step. `.debug_info`/`.debug_abbrev` are copied through
+//! unmodified, so DIE-level data (variable names, types, locations) still
+//! describes the original Wasm byte offsets rather than the JIT's
+//! stack/register layout; resolving pointer-typed variable locations through
+//! `ModuleMemoryOffset` is left for a follow-up once line-level stepping is
+//! in use.
+
+use gimli::{LineEncoding, RunTimeEndian};
+use wasmer_types::{FunctionAddressMap, LocalFunctionIndex, ModuleMemoryOffset};
+
+/// Maps a single compiled function's generated machine-code range back to the
+/// Wasm offsets recorded in its address map, so the DWARF transform below
+/// knows which original line/variable records to rewrite for that range.
+pub struct FunctionAddressTransform {
+    pub local_index: LocalFunctionIndex,
+    pub body_offset: u32,
+    pub body_len: u32,
+    pub address_map: FunctionAddressMap,
+}
+
+impl FunctionAddressTransform {
+    /// Translates a Wasm source offset that falls inside this function into
+    /// the generated code offset of the nearest instruction emitted for it,
+    /// or `None` if the offset isn't covered by this function at all.
+    fn generated_offset_for(&self, wasm_offset: u32) -> Option<u32> {
+        if wasm_offset < self.address_map.start_srcloc.bits()
+            || wasm_offset >= self.address_map.end_srcloc.bits()
+        {
+            return None;
+        }
+        self.address_map
+            .instructions
+            .iter()
+            .filter(|entry| entry.srcloc.bits() <= wasm_offset)
+            .last()
+            .map(|entry| self.body_offset + entry.code_offset as u32)
+    }
+}
+
+/// The rewritten DWARF sections, ready to be pushed onto `custom_sections`
+/// alongside the `eh_frame` unwind table.
+pub struct TransformedDwarf {
+    pub debug_info: Vec<u8>,
+    pub debug_line: Vec<u8>,
+    pub debug_abbrev: Vec<u8>,
+}
+
+/// Rewrites the Wasm DWARF found in `raw_debug_sections` (keyed by section
+/// name, e.g. `.debug_info`, `.debug_line`, `.debug_abbrev`) so that its line
+/// program addresses point at generated machine code instead of Wasm byte
+/// offsets, using `address_transforms` to relate the two.
+///
+/// Returns `None` if the module carries no Wasm `.debug_line` section, if it
+/// fails to parse as a valid line-number program, if none of its rows falls
+/// inside a compiled function, or if writing the rewritten program fails.
+pub fn generate_debug_info(
+    raw_debug_sections: &[(&str, &[u8])],
+    address_transforms: &[FunctionAddressTransform],
+    memory_offset: &ModuleMemoryOffset,
+) -> Option<TransformedDwarf> {
+    // Pointer-typed variable locations aren't rewritten yet (see module docs);
+    // this keeps the parameter live for that follow-up without being used.
+    let _ = memory_offset;
+
+    let debug_line = raw_debug_sections
+        .iter()
+        .find(|(name, _)| *name == ".debug_line")
+        .map(|(_, data)| *data)?;
+    let debug_info = raw_debug_sections
+        .iter()
+        .find(|(name, _)| *name == ".debug_info")
+        .map(|(_, data)| *data)
+        .unwrap_or(&[]);
+    let debug_abbrev = raw_debug_sections
+        .iter()
+        .find(|(name, _)| *name == ".debug_abbrev")
+        .map(|(_, data)| *data)
+        .unwrap_or(&[]);
+
+    let transformed_line = transform_debug_line(debug_line, address_transforms)?;
+
+    Some(TransformedDwarf {
+        debug_info: debug_info.to_vec(),
+        debug_line: transformed_line,
+        debug_abbrev: debug_abbrev.to_vec(),
+    })
+}
+
+/// Parses the Wasm module's `.debug_line` program with `gimli::read`, remaps
+/// every row's address through the function whose Wasm range contains it,
+/// and re-emits a new line program with `gimli::write` using the generated
+/// addresses. Rows that don't fall inside any known function are dropped,
+/// since there's no generated code for a debugger to stop at.
+fn transform_debug_line(
+    raw_debug_line: &[u8],
+    address_transforms: &[FunctionAddressTransform],
+) -> Option<Vec<u8>> {
+    let endian = RunTimeEndian::Little;
+    let debug_line = gimli::read::DebugLine::new(raw_debug_line, endian);
+
+    // The Wasm toolchain emits a single compilation unit's line program for
+    // the whole module; `offset` 0 is where it starts.
+    let program = debug_line
+        .program(
+            gimli::DebugLineOffset(0),
+            gimli::Encoding {
+                format: gimli::Format::Dwarf32,
+                version: 4,
+                address_size: 8,
+            }
+            .address_size,
+            None,
+            None,
+        )
+        .ok()?;
+
+    let mut rows = program.rows();
+    let mut remapped_rows = Vec::new();
+    while let Ok(Some((_, row))) = rows.next_row() {
+        let wasm_offset = row.address() as u32;
+        if let Some(transform) = address_transforms
+            .iter()
+            .find(|t| t.generated_offset_for(wasm_offset).is_some())
+        {
+            if let Some(generated_offset) = transform.generated_offset_for(wasm_offset) {
+                remapped_rows.push((
+                    generated_offset as u64,
+                    row.line().map(|l| l.get()).unwrap_or(0),
+                    row.column(),
+                    row.is_stmt(),
+                    row.end_sequence(),
+                ));
+            }
+        }
+    }
+    if remapped_rows.is_empty() {
+        return None;
+    }
+    remapped_rows.sort_by_key(|(address, ..)| *address);
+
+    let encoding = gimli::Encoding {
+        format: gimli::Format::Dwarf32,
+        version: 4,
+        address_size: 8,
+    };
+    let mut out_program = gimli::write::LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        gimli::write::LineString::new(&b"<wasm>"[..], encoding, &mut Default::default()),
+        gimli::write::LineString::new(&b"<generated>"[..], encoding, &mut Default::default()),
+        None,
+    );
+    out_program.begin_sequence(None);
+    for (address, line, column, is_stmt, end_sequence) in remapped_rows {
+        let row = out_program.row();
+        row.address_offset = address;
+        row.line = line;
+        row.column = match column {
+            gimli::ColumnType::LeftEdge => 0,
+            gimli::ColumnType::Column(c) => c.get(),
+        };
+        row.is_stmt = is_stmt;
+        row.end_sequence = end_sequence;
+        out_program.generate_row();
+    }
+
+    // This module only ever produces one line program for the whole unit, so
+    // write it directly into a fresh `.debug_line` section rather than
+    // threading a whole `Dwarf`/`Unit` object through for a single sequence.
+    let mut debug_line_out = gimli::write::DebugLine::from(gimli::write::EndianVec::new(endian));
+    out_program
+        .write(&mut debug_line_out, encoding, &mut Default::default())
+        .ok()?;
+
+    Some(debug_line_out.0.into_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmer_types::{InstructionAddressMap, SourceLoc};
+
+    const ENCODING: gimli::Encoding = gimli::Encoding {
+        format: gimli::Format::Dwarf32,
+        version: 4,
+        address_size: 8,
+    };
+
+    /// Builds a standalone `.debug_line` section with a single row at
+    /// `wasm_address`, mirroring the shape `transform_debug_line` expects to
+    /// parse (one compilation unit's line program starting at offset 0).
+    fn debug_line_with_one_row(wasm_address: u64) -> Vec<u8> {
+        let mut program = gimli::write::LineProgram::new(
+            ENCODING,
+            LineEncoding::default(),
+            gimli::write::LineString::new(&b"<wasm>"[..], ENCODING, &mut Default::default()),
+            gimli::write::LineString::new(&b"<generated>"[..], ENCODING, &mut Default::default()),
+            None,
+        );
+        program.begin_sequence(None);
+        {
+            let row = program.row();
+            row.address_offset = wasm_address;
+            row.line = 7;
+            row.column = 1;
+            row.is_stmt = true;
+        }
+        program.generate_row();
+
+        let mut out = gimli::write::DebugLine::from(gimli::write::EndianVec::new(
+            RunTimeEndian::Little,
+        ));
+        program
+            .write(&mut out, ENCODING, &mut Default::default())
+            .expect("writing a single-row line program must succeed");
+        out.0.into_vec()
+    }
+
+    #[test]
+    fn transform_debug_line_remaps_row_into_generated_code() {
+        let raw_debug_line = debug_line_with_one_row(0x10);
+
+        let address_map = FunctionAddressMap {
+            instructions: vec![InstructionAddressMap {
+                srcloc: SourceLoc::new(0x10),
+                code_offset: 4,
+                code_len: 2,
+            }],
+            start_srcloc: SourceLoc::new(0),
+            end_srcloc: SourceLoc::new(0x20),
+            body_offset: 0,
+            body_len: 8,
+        };
+        let transform = FunctionAddressTransform {
+            local_index: LocalFunctionIndex::new(0),
+            body_offset: 100,
+            body_len: 8,
+            address_map,
+        };
+
+        let transformed =
+            transform_debug_line(&raw_debug_line, &[transform]).expect("row falls inside the one known function");
+
+        // The single row should now point at `body_offset + code_offset`
+        // (100 + 4 = 104) instead of its original Wasm offset (0x10).
+        let debug_line = gimli::read::DebugLine::new(&transformed, RunTimeEndian::Little);
+        let program = debug_line
+            .program(gimli::DebugLineOffset(0), 8, None, None)
+            .expect("the rewritten section must itself be a valid line program");
+        let mut rows = program.rows();
+        let (_, row) = rows
+            .next_row()
+            .expect("parsing the rewritten row must succeed")
+            .expect("a row was written");
+        assert_eq!(row.address(), 104);
+    }
+
+    #[test]
+    fn transform_debug_line_drops_rows_outside_every_known_function() {
+        let raw_debug_line = debug_line_with_one_row(0x10);
+        let address_map = FunctionAddressMap {
+            instructions: vec![InstructionAddressMap {
+                srcloc: SourceLoc::new(0x999),
+                code_offset: 0,
+                code_len: 2,
+            }],
+            start_srcloc: SourceLoc::new(0x999),
+            end_srcloc: SourceLoc::new(0x9a0),
+            body_offset: 0,
+            body_len: 8,
+        };
+        let transform = FunctionAddressTransform {
+            local_index: LocalFunctionIndex::new(0),
+            body_offset: 0,
+            body_len: 8,
+            address_map,
+        };
+
+        assert!(transform_debug_line(&raw_debug_line, &[transform]).is_none());
+    }
+}
@@ -0,0 +1,222 @@
+//! Shared per-function translation state and codegen helpers used while
+//! lowering a single Wasm function body to Cranelift IR. `FuncTranslator`
+//! (in `translator.rs`) drives the `cranelift_wasm` translation and calls
+//! into the hooks below at the corresponding points.
+
+use crate::config::InterruptGranularity;
+use cranelift_codegen::ir;
+use cranelift_codegen::isa::TargetFrontendConfig;
+use cranelift_frontend::FunctionBuilder;
+use std::collections::HashMap;
+use wasmer_types::entity::PrimaryMap;
+use wasmer_types::{
+    BuiltinIndex, FunctionIndex, MemoryIndex, MemoryStyle, ModuleInfo, SectionIndex,
+    SignatureIndex, TableIndex, TableStyle, VMOffsets,
+};
+
+/// Per-function translation environment: the module-wide context
+/// (signatures, memory/table styles) plus the bits of mutable state a single
+/// function's translation accumulates (e.g. loop-counters for interrupt
+/// checks with [`InterruptGranularity::CounterThreshold`]).
+pub struct FuncEnvironment<'module_environment> {
+    frontend_config: TargetFrontendConfig,
+    module: &'module_environment ModuleInfo,
+    signatures: &'module_environment PrimaryMap<SignatureIndex, ir::Signature>,
+    memory_styles: &'module_environment PrimaryMap<MemoryIndex, MemoryStyle>,
+    table_styles: &'module_environment PrimaryMap<TableIndex, TableStyle>,
+    offsets: VMOffsets,
+    enable_interrupts: bool,
+    interrupt_granularity: InterruptGranularity,
+    /// Per-loop back-edge counters, used only for
+    /// `InterruptGranularity::CounterThreshold`; keyed by the Cranelift
+    /// `Block` that starts the loop.
+    loop_counters: HashMap<ir::Block, ir::Value>,
+}
+
+impl<'module_environment> FuncEnvironment<'module_environment> {
+    pub fn new(
+        frontend_config: TargetFrontendConfig,
+        module: &'module_environment ModuleInfo,
+        signatures: &'module_environment PrimaryMap<SignatureIndex, ir::Signature>,
+        memory_styles: &'module_environment PrimaryMap<MemoryIndex, MemoryStyle>,
+        table_styles: &'module_environment PrimaryMap<TableIndex, TableStyle>,
+    ) -> Self {
+        Self {
+            frontend_config,
+            module,
+            signatures,
+            memory_styles,
+            table_styles,
+            offsets: VMOffsets::new(frontend_config.pointer_bytes(), module),
+            enable_interrupts: false,
+            interrupt_granularity: InterruptGranularity::EveryBackEdge,
+            loop_counters: HashMap::new(),
+        }
+    }
+
+    /// Enables emission of cooperative interruption checks for this
+    /// function's translation, matching `Cranelift::enable_interrupts`.
+    pub fn set_interrupts(&mut self, enabled: bool, granularity: InterruptGranularity) {
+        self.enable_interrupts = enabled;
+        self.interrupt_granularity = granularity;
+    }
+
+    pub fn target_config(&self) -> TargetFrontendConfig {
+        self.frontend_config
+    }
+
+    /// Maps a Wasm `funcref`/`externref` to Cranelift's reference IR type
+    /// (`R32`/`R64`, matching the target's pointer width). This is the only
+    /// "marking" a reference-typed value needs: once a local, parameter, or
+    /// intermediate SSA value carries a reference IR type, Cranelift's
+    /// safepoint machinery (enabled by `enable_safepoints` in
+    /// `Cranelift::isa`) automatically includes it in the stack map of every
+    /// call it's live across, without any further per-value registration.
+    ///
+    /// `crate::translator::signature_to_cranelift_ir` applies this same rule
+    /// to reference-typed function parameters and results, so a value stays
+    /// reference-typed (and so stays in the stack map) across a call
+    /// boundary. Reference-typed *locals* declared inside a function body
+    /// still need this called from inside Wasm operator translation itself —
+    /// that requires the `cranelift_wasm::FuncEnvironment` trait impl noted
+    /// in `translator.rs`, which isn't there yet.
+    pub fn reference_type(&self) -> ir::Type {
+        match self.frontend_config.pointer_type() {
+            ir::types::I32 => ir::types::R32,
+            ir::types::I64 => ir::types::R64,
+            other => panic!("unsupported pointer type {:?} for a reference type", other),
+        }
+    }
+
+    /// Emits a cooperative-interruption check: load the shared interruption
+    /// flag out of the `vmctx`, compare it against zero, and trap with
+    /// `Interrupt` if it's set. Deliberately cheap — a single load, compare,
+    /// and conditional trap — so it's safe to place on every loop back-edge.
+    ///
+    /// Not reachable yet: placing this at a loop back-edge needs a hook from
+    /// the Wasm translation loop itself (e.g. `translate_loop_header` on
+    /// `cranelift_wasm::FuncEnvironment`), and the `cranelift_wasm` this
+    /// crate depends on doesn't have one. `InterruptGranularity::CounterThreshold`
+    /// has no effect until that hook exists; see
+    /// [`FuncEnvironment::insert_entry_interrupt_check`] for the check that
+    /// *is* wired in today, at function entry only.
+    pub fn emit_interrupt_check(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        vmctx: ir::Value,
+        loop_header: Option<ir::Block>,
+    ) {
+        if !self.enable_interrupts {
+            return;
+        }
+
+        if let (Some(header), InterruptGranularity::CounterThreshold(threshold)) =
+            (loop_header, self.interrupt_granularity)
+        {
+            let counter = *self.loop_counters.entry(header).or_insert_with(|| {
+                builder.ins().iconst(ir::types::I32, 0)
+            });
+            let incremented = builder.ins().iadd_imm(counter, 1);
+            self.loop_counters.insert(header, incremented);
+            let hit_threshold =
+                builder
+                    .ins()
+                    .icmp_imm(ir::condcodes::IntCC::UnsignedGreaterThanOrEqual, incremented, threshold as i64);
+            // Only the slow path (flag load + trap) is gated by the
+            // threshold; the counter itself is updated unconditionally so
+            // back-edges taken after a check still count towards the next
+            // one.
+            let check_block = builder.create_block();
+            let continue_block = builder.create_block();
+            builder
+                .ins()
+                .brif(hit_threshold, check_block, &[], continue_block, &[]);
+            builder.switch_to_block(check_block);
+            self.load_flag_and_trap(builder, vmctx);
+            builder.ins().jump(continue_block, &[]);
+            builder.switch_to_block(continue_block);
+            builder.seal_block(check_block);
+            builder.seal_block(continue_block);
+        } else {
+            self.load_flag_and_trap(builder, vmctx);
+        }
+    }
+
+    /// Allocates a reference to out-of-line read-only data spilled into a
+    /// `custom_sections` entry — e.g. a large switch-table jump table that's
+    /// cheaper to store once and relocate against than to inline at every
+    /// use. `mach_reloc_to_reloc` decodes this namespace-1 name back into
+    /// `RelocationTarget::CustomSection`.
+    ///
+    /// Not reachable yet: lowering a `br_table` into an out-of-line jump
+    /// table happens inside `cranelift_wasm`'s own operator translation,
+    /// which has no hook back into this crate for it. Needs a `cranelift_wasm`
+    /// change, same blocking dependency as `emit_interrupt_check`'s loop
+    /// back-edge case.
+    pub fn custom_section_reference(&self, section: SectionIndex) -> ir::ExternalName {
+        ir::ExternalName::user(1, section.as_u32())
+    }
+
+    /// Allocates a reference to a VM builtin helper function, giving calls
+    /// into the runtime a stable ABI instead of routing them through
+    /// libcalls. `mach_reloc_to_reloc` decodes this namespace-2 name back
+    /// into `RelocationTarget::Builtin`.
+    ///
+    /// Not reachable from Wasm-level translation yet, for the same reason as
+    /// `custom_section_reference` — see that doc comment.
+    pub fn builtin_reference(&self, builtin: BuiltinIndex) -> ir::ExternalName {
+        ir::ExternalName::user(2, builtin.as_u32())
+    }
+
+    /// Inserts the cooperative-interruption check at the entry of an already
+    /// fully-translated function, right before any of its real instructions
+    /// run. This is the one interruption checkpoint this crate can place
+    /// without a `cranelift_wasm` hook: the entry block and its parameters
+    /// (including `vmctx`) are known as soon as translation finishes,
+    /// regardless of how the body was translated. It bounds how long a
+    /// *newly entered* call can run without observing the flag, but — unlike
+    /// the loop back-edge check `emit_interrupt_check` is meant for — it does
+    /// nothing for a call already inside a long-running loop.
+    ///
+    /// Called by `FuncTranslator::translate` after the Wasm body has been
+    /// translated into `func`, and only when interruption is enabled.
+    pub fn insert_entry_interrupt_check(&self, func: &mut ir::Function) {
+        if !self.enable_interrupts {
+            return;
+        }
+        let entry_block = func
+            .layout
+            .entry_block()
+            .expect("a translated function always has an entry block");
+        let vmctx = func.dfg.block_params(entry_block)[0];
+        let flag_offset = self.offsets.vmctx_interrupt_flag();
+
+        let mut pos = cranelift_codegen::cursor::FuncCursor::new(func)
+            .at_first_insertion_point(entry_block);
+        let flag = pos.ins().load(
+            ir::types::I32,
+            ir::MemFlags::trusted(),
+            vmctx,
+            flag_offset as i32,
+        );
+        pos.ins().trapnz(flag, ir::TrapCode::Interrupt);
+    }
+
+    fn load_flag_and_trap(&self, builder: &mut FunctionBuilder, vmctx: ir::Value) {
+        let flag_offset = self.offsets.vmctx_interrupt_flag();
+        let flag = builder.ins().load(
+            ir::types::I32,
+            ir::MemFlags::trusted(),
+            vmctx,
+            flag_offset as i32,
+        );
+        builder.ins().trapnz(flag, ir::TrapCode::Interrupt);
+    }
+}
+
+/// The Cranelift external name used for a local or imported function, shared
+/// by `compile_module` (to name the function being compiled) and the
+/// trampoline builders (to name a call target).
+pub fn get_function_name(function_index: FunctionIndex) -> ir::ExternalName {
+    ir::ExternalName::user(0, function_index.as_u32())
+}